@@ -1,28 +1,76 @@
-use num::BigInt;
+use num::{CheckedAdd, CheckedMul, CheckedSub};
+use std::fmt::Debug;
 
-/// Struct for tracking and managing a Fibonacci sequence.
+/// Numeric types a `Fibonacci` sequence can be backed by. `CheckedAdd`/`CheckedSub`/`CheckedMul`
+/// let bounded integer types (`u64`, `u128`, ...) end the iterator (or the `nth` seek) on
+/// overflow/underflow instead of panicking or wrapping, while `num::BigInt` never fails any of
+/// them and so iterates forever. `From<u8>` supplies the small `0`/`1`/`2` bootstrap constants
+/// the sequence needs.
+pub trait FibNum: Clone + Debug + PartialEq + From<u8> + CheckedAdd + CheckedSub + CheckedMul {}
+
+impl<T> FibNum for T where T: Clone + Debug + PartialEq + From<u8> + CheckedAdd + CheckedSub + CheckedMul {}
+
+/// Struct for tracking and managing a Fibonacci sequence, generic over the numeric type backing
+/// it. Instantiate as `Fibonacci::<u64>::new()`, `Fibonacci::<u128>::new()`, or
+/// `Fibonacci::<BigInt>::new()` depending on whether callers want bounded-but-fast arithmetic or
+/// an unbounded sequence.
+///
+/// Internally this only ever holds the two neighboring values of the sequence (`prev` and
+/// `curr`) plus a signed `index`, rather than the whole history. `curr` is always `F(index)` and
+/// `prev` is always `F(index - 1)`, for every `index` the sequence has visited, negative indices
+/// (negafibonacci) included. `next`/`previous` are then just the forward/backward step of that
+/// invariant, with no bootstrap or boundary special-casing required.
+///
+/// `prev` starts out `None`: before any step it would be `F(-2) = -1`, which unsigned backends
+/// like `u64`/`u128` simply cannot hold, so it can't be computed eagerly in `new`. A first
+/// `next` doesn't need it (it returns `F(0) = 0` directly); a first `previous` derives it
+/// lazily via `checked_sub` like every other boundary case — so a bounded backend ends the
+/// sequence there instead of panicking on construction.
 #[derive(Debug)]
-pub struct Fibonacci {
-    full: Vec<BigInt>,
-    count: usize
+pub struct Fibonacci<T: FibNum> {
+    prev: Option<T>,
+    curr: T,
+    index: i64
 }
 
-impl Fibonacci {
-    /// Returns an initialized instance of Fibonacci.
-    pub fn new() -> Fibonacci {
+impl<T: FibNum> Fibonacci<T> {
+    /// Returns an initialized instance of Fibonacci, positioned one step before `F(0)` so that
+    /// the first call to `next` returns `F(0)`.
+    pub fn new() -> Fibonacci<T> {
         Fibonacci {
-            full: vec!(BigInt::from(0)),
-            count: 0
+            prev: None,
+            curr: T::from(1), // F(-1), used internally once a first step is taken.
+            index: -1
         }
     }
 
-    /// Returns the previous item in the sequence and steps the sequence back so that a subsequent
-    /// call to next will a give a repeat value.
+    /// Returns the next item in the sequence and advances it by one step via
+    /// `(prev, curr) = (curr, prev + curr)`. For a bounded backend this ends the iterator
+    /// (`None`) the moment the next value would overflow the type; `BigInt` never does.
+    pub fn next(&mut self) -> Option<T> {
+        // F(0) = 0 always, so the very first step needs no arithmetic (and no `F(-2)`) at all.
+        let new_curr = match &self.prev {
+            Some(prev) => prev.checked_add(&self.curr)?,
+            None => T::from(0)
+        };
+        self.prev = Some(self.curr.clone());
+        self.curr = new_curr.clone();
+        self.index += 1;
+        Some(new_curr)
+    }
+
+    /// Returns the previous item in the sequence and steps it back by one via the inverse of
+    /// `next`, `(prev, curr) = (curr - prev, prev)`. Stepping past zero keeps going rather than
+    /// clamping: the sequence continues into the negafibonacci extension, where
+    /// `F(-n) = (-1)^(n+1) * F(n)`, i.e. `0, 1, -1, 2, -3, 5, -8, 13, ...` as the index keeps
+    /// decreasing. For a bounded backend, stepping backward needs the *next* lookahead value
+    /// (`F(index - 2)`) in hand, so the iterator ends as soon as that lookahead itself goes
+    /// negative, even if the value just returned was still representable.
     ///
     /// Example:
     ///
     /// ```
-    /// let fibonacci = Fibonacci::new();
+    /// let fibonacci = Fibonacci::<BigInt>::new();
     /// fibonacci.next(); // 0
     /// fibonacci.next(); // 1
     /// fibonacci.next(); // 1
@@ -30,131 +78,90 @@ impl Fibonacci {
     /// fibonacci.previous(); // 1
     /// fibonacci.next(); // 2
     /// ```
-    pub fn previous(&mut self) -> Option<BigInt> {
-        // A desired side-effect of this is that running previous on a Fibonacci that has been
-        // previoused to zero fully resets the next funtion to previous as well.
-        if self.count == 0 {
-            Some(BigInt::from(0))
+    pub fn previous(&mut self) -> Option<T> {
+        // No step has been taken yet, so `prev` (F(-2)) hasn't been derived; get it the same way
+        // `next` would have, via F(-2) = F(0) - F(-1), instead of storing it eagerly in `new`.
+        let prev = match &self.prev {
+            Some(prev) => prev.clone(),
+            None => T::from(0).checked_sub(&self.curr)?
+        };
+        let new_prev = self.curr.checked_sub(&prev)?;
+        let new_curr = prev;
+        self.prev = Some(new_prev);
+        self.curr = new_curr.clone();
+        self.index -= 1;
+        Some(new_curr)
+    }
+
+    /// Returns the current fibonacci value without changing it, i.e. `F(index)`. Before the
+    /// first `next`/`previous` call this reads `0` rather than the internal `F(-1)` placeholder,
+    /// matching the value that call will go on to produce.
+    pub fn current(&self) -> Option<T> {
+        if self.prev.is_none() {
+            Some(T::from(0))
         } else {
-            self.count -= 1;
-            match self.full.len() {
-                // If there are 3 items in the full Vector we are either in the normal case or in a
-                // [0,1,1] case.
-                3 => {
-                    // Safe to unwrap because we have already validated a length of 3.
-                    match self.full.get(0).unwrap().to_string().as_str() {
-                        // If we have 3 items and the one at index 0 is 0, we should have [0,1,1].
-                        // This is a special case, as usual.
-                        "0" => {
-                            self.full = vec!(BigInt::from(0), BigInt::from(1));
-                            // Because of the way the the initial numbers are nexted through, we
-                            // could either be coming from count 2 to count 1 OR from count 3 to
-                            // count 2 in this case. We should hanlde both for the user even if
-                            // it's an inelegant edge case.
-                            if self.count == 1 {
-                                Some(BigInt::from(0))
-                            } else {
-                                Some(BigInt::from(1))
-                            }
-                        },
-                        // If the item at index 0 is anything but 0, we are in a normal case.
-                        _ => {
-                            let target_last = self.full.get(1).unwrap().clone();
-                            let target_middle = self.full.get(0).unwrap().clone();
-                            // The first item in the new Vector is the actial previous value at index
-                            // 1 less the further previous value at index 0. This approach saves the
-                            // memory overhead of keeping the entire iteration history.
-                            let target_first = &target_last - &target_middle;
-                            let new_vec = vec!(target_first, target_middle, target_last);
-                            self.full = new_vec;
-                            match self.full.last() {
-                                Some(val) => Some(val.clone()),
-                                None => None
-                            }
-                        }
-                    }
-                },
-                // If there are only 2 items in the full Vector we are in a [0,1] case, making the
-                // desired previous value 0.
-                2 => {
-                    self.full.pop();
-                    Some(BigInt::from(0))
-                },
-                // If there is only 1 item in the full Vector we are in a [0] case. We should never
-                // go lower than this.
-                1 => Some(BigInt::from(0)),
-                _ => None
-            }
+            Some(self.curr.clone())
         }
     }
 
-    /// Returns the current fibonacci value without changing it. Generally, this is achieved by
-    /// taking the last item in the `full` Vector, which contains the last 3 items in the sequence
-    /// in most cases. See code for exceptions.
-    pub fn current(&self) -> Option<BigInt> {
-        // The first call to next adds a 1 to the sequence even though it returns 0 for the sake of
-        // completeness. We have to manually ignore this.
-        if self.full.len() == 2 {
-            Some(BigInt::from(0))
-        }
-        // On the second iteration the Vector is full, but is still in the weird state caused by
-        // the first few numbers. One last hard coded value.
-        else if self.full.len() == 3 && self.count == 2 {
-            Some(BigInt::from(1))
-        }
-        // After count: 2 we can use the last item in the full Vector.
-        else {
-            match self.full.last() {
-                Some(val) => Some(val.clone()),
-                None => Some(BigInt::from(0))
+    /// Returns the internal call count, i.e. how many times `next` has net been called on this
+    /// sequence (accounting for `previous`). Combined with `nth`, this is enough to reconstruct
+    /// an equivalent `Fibonacci` later without keeping the instance itself around.
+    pub fn count(&self) -> i64 {
+        self.index + 1
+    }
+
+    /// Repositions the sequence to index `n` in O(log n) time using the fast-doubling
+    /// identities, instead of walking there one `next` at a time. Returns `None`, leaving the
+    /// sequence untouched, if `F(n)` or `F(n+1)` doesn't fit in a bounded backend; `BigInt`
+    /// never fails this.
+    ///
+    /// Given `(F(k), F(k+1))`, fast doubling derives `F(2k) = F(k) * (2*F(k+1) - F(k))` and
+    /// `F(2k+1) = F(k)^2 + F(k+1)^2`, then for each bit of `n` from most- to least-significant
+    /// it selects `(F(2k), F(2k+1))` when the bit is 0 or `(F(2k+1), F(2k)+F(2k+1))` when the
+    /// bit is 1, starting from `(F(0), F(1))`. After landing on `(F(n), F(n+1))`, `prev`/`curr`
+    /// and `index` are set so a subsequent `next`/`previous`/`current` behaves exactly as if we
+    /// had walked here one step at a time.
+    pub fn nth(&mut self, n: u64) -> Option<()> {
+        let (f_n, f_n_plus_1) = Self::fast_doubling(n)?;
+        let prev = f_n_plus_1.checked_sub(&f_n)?;
+        self.prev = Some(prev);
+        self.curr = f_n;
+        self.index = n as i64;
+        Some(())
+    }
+
+    /// Computes `(F(n), F(n+1))` via fast doubling, walking the bits of `n` from most- to
+    /// least-significant, using checked arithmetic throughout so a bounded backend ends the seek
+    /// (`None`) the moment an intermediate doubling step would itself overflow, rather than
+    /// panicking or wrapping partway through.
+    fn fast_doubling(n: u64) -> Option<(T, T)> {
+        let two = T::from(2);
+        let mut f_k = T::from(0);
+        let mut f_k_plus_1 = T::from(1);
+        for i in (0..u64::BITS).rev() {
+            let double_f_k_plus_1_minus_f_k = two.checked_mul(&f_k_plus_1)?.checked_sub(&f_k)?;
+            let f_2k = f_k.checked_mul(&double_f_k_plus_1_minus_f_k)?;
+            let f_2k_plus_1 = f_k.checked_mul(&f_k)?.checked_add(&f_k_plus_1.checked_mul(&f_k_plus_1)?)?;
+            if (n >> i) & 1 == 1 {
+                let next_f_k_plus_1 = f_2k.checked_add(&f_2k_plus_1)?;
+                f_k = f_2k_plus_1;
+                f_k_plus_1 = next_f_k_plus_1;
+            } else {
+                f_k = f_2k;
+                f_k_plus_1 = f_2k_plus_1;
             }
         }
+        Some((f_k, f_k_plus_1))
     }
+
 }
 
-impl Iterator for Fibonacci {
-    type Item = BigInt;
+impl<T: FibNum> Iterator for Fibonacci<T> {
+    type Item = T;
 
-    /// Return the next number in the Fibonacci sequence. This function has no call limit and will
-    /// return accurate values as long as there are resources to hold them.
     fn next(&mut self) -> Option<Self::Item> {
-        self.count += 1;
-        // Because we bootstrap with a single 0 in the Vector, we need to account for the first
-        // couple of iterations where we need to fill it to our intended maximum. Remember that the
-        // first values of the sequence are, irritatingly, [0, 1, 1, 2].
-        if self.full.len() == 1 {
-            self.full.push(BigInt::from(1));
-            // Because of the initial divergence between count and the number of next calls, we could
-            // either be coming from count 0 to count 1 OR from count 1 to count 2 in this case. We
-            // should hanlde both for the user even if it's an inelegant edge case.
-            if self.count == 1 {
-                Some(BigInt::from(0))
-            } else {
-                Some(BigInt::from(1))
-            }
-        } else if self.full.len() == 2 {
-            let next_val: BigInt = self.full.iter().sum();
-            self.full.push(next_val.clone());
-            Some(next_val)
-        } else if self.full.len() == 3 && self.count == 3 {
-            Some(BigInt::from(1))
-        } else {
-            // If we have a fully initialized set it is time to actually do the fibonacci math with
-            // the contents of the Vector. Get the last 2 items in the Vector, sum them. Here we
-            // have some additional guards for unexpected Vector and subslice states that handle
-            // unlikely cases.
-            let length = self.full.len();
-            match self.full.get(length - 2..length) {
-                Some(subslice) => {
-                    let next_num: BigInt = subslice.iter().sum();
-                    let mut new_vec = subslice.to_vec();
-                    new_vec.push(next_num.clone());
-                    self.full = new_vec;
-                    Some(next_num)
-                },
-                None => Some(BigInt::from(0))
-            }
-        }
+        self.next()
     }
 }
 
@@ -163,10 +170,11 @@ impl Iterator for Fibonacci {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use num::BigInt;
 
     #[test]
     fn verify_first_10_nexts() {
-        let mut fibonacci = Fibonacci::new();
+        let mut fibonacci = Fibonacci::<BigInt>::new();
         assert_eq!(fibonacci.next().unwrap(), BigInt::from(0));
         assert_eq!(fibonacci.next().unwrap(), BigInt::from(1));
         assert_eq!(fibonacci.next().unwrap(), BigInt::from(1));
@@ -181,7 +189,7 @@ mod tests {
 
     #[test]
     fn verify_1000th() {
-        let mut fibonacci = Fibonacci::new();
+        let mut fibonacci = Fibonacci::<BigInt>::new();
         let mut count = 0;
         loop {
             count += 1;
@@ -201,9 +209,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn verify_current_before_any_next_or_previous() {
+        // A fresh sequence is positioned one step before F(0), not at it -- `current` should
+        // still read 0 here, the value the first `next` call will go on to produce, rather than
+        // leaking the internal F(-1) placeholder `new` bootstraps with.
+        let fibonacci = Fibonacci::<BigInt>::new();
+        assert_eq!(fibonacci.current().unwrap(), BigInt::from(0));
+
+        let fibonacci = Fibonacci::<u64>::new();
+        assert_eq!(fibonacci.current().unwrap(), 0);
+    }
+
     #[test]
     fn verify_first_10_currents() {
-        let mut fibonacci = Fibonacci::new();
+        let mut fibonacci = Fibonacci::<BigInt>::new();
         fibonacci.next();
         assert_eq!(fibonacci.current().unwrap(), BigInt::from(0));
         fibonacci.next();
@@ -228,7 +248,7 @@ mod tests {
 
     #[test]
     fn verify_first_10_previouses() {
-        let mut fibonacci = Fibonacci::new();
+        let mut fibonacci = Fibonacci::<BigInt>::new();
         let mut count = 0;
         loop {
             count +=1;
@@ -245,14 +265,28 @@ mod tests {
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(1));
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(1));
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(0));
-        // Should never go below 0
-        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(0));
-        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(0));
+        // Past the origin the sequence keeps going: negafibonacci values instead of a repeating 0.
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(1));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(-1));
+    }
+
+    #[test]
+    fn verify_negafibonacci_previouses() {
+        let mut fibonacci = Fibonacci::<BigInt>::new();
+        fibonacci.next();
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(1));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(-1));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(2));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(-3));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(5));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(-8));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(13));
+        assert_eq!(fibonacci.previous().unwrap(), BigInt::from(-21));
     }
 
     #[test]
     fn verify_early_forward_and_back() {
-        let mut fibonacci = Fibonacci::new();
+        let mut fibonacci = Fibonacci::<BigInt>::new();
         assert_eq!(fibonacci.next().unwrap(), BigInt::from(0));
         assert_eq!(fibonacci.next().unwrap(), BigInt::from(1));
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(0));
@@ -267,4 +301,77 @@ mod tests {
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(1));
         assert_eq!(fibonacci.previous().unwrap(), BigInt::from(0));
     }
+
+    #[test]
+    fn verify_nth_matches_walked_sequence() {
+        let mut walked = Fibonacci::<BigInt>::new();
+        for _ in 0..=20 {
+            walked.next();
+        }
+
+        let mut jumped = Fibonacci::<BigInt>::new();
+        jumped.nth(20);
+        assert_eq!(jumped.current(), walked.current());
+        assert_eq!(jumped.next(), walked.next());
+        assert_eq!(jumped.previous(), walked.previous());
+        assert_eq!(jumped.previous(), walked.previous());
+    }
+
+    #[test]
+    fn verify_nth_bootstrap_quirk() {
+        let mut jumped = Fibonacci::<BigInt>::new();
+        jumped.nth(0);
+        assert_eq!(jumped.current().unwrap(), BigInt::from(0));
+
+        let mut jumped = Fibonacci::<BigInt>::new();
+        jumped.nth(1);
+        assert_eq!(jumped.current().unwrap(), BigInt::from(1));
+    }
+
+    #[test]
+    fn verify_nth_1000th() {
+        let mut fibonacci = Fibonacci::<BigInt>::new();
+        fibonacci.nth(999);
+        assert_eq!(
+            fibonacci.current()
+                .unwrap()
+                .clone()
+                .to_string(),
+                String::from("26863810024485359386146727202142923967616609318986952340123175997617981700247881689338369654483356564191827856161443356312976673642210350324634850410377680367334151172899169723197082763985615764450078474174626")
+        );
+    }
+
+    #[test]
+    fn verify_u64_backend_overflows_instead_of_wrapping() {
+        let mut fibonacci = Fibonacci::<u64>::new();
+        // F(93) = 12200160415121876738 still fits in a u64, but F(94) does not, so walking past
+        // index 93 must end the iterator rather than wrap.
+        for _ in 0..94 {
+            assert!(fibonacci.next().is_some());
+        }
+        assert_eq!(fibonacci.next(), None);
+    }
+
+    #[test]
+    fn verify_u64_backend_previous_ends_at_boundary() {
+        let mut fibonacci = Fibonacci::<u64>::new();
+        fibonacci.next();
+        // Stepping back needs the lookahead value F(-2) = -1 in hand to keep going, and that's
+        // unrepresentable in u64, so the iterator ends here even though F(-1) = 1 (the value
+        // this call would otherwise return) is itself in range.
+        assert_eq!(fibonacci.previous(), None);
+    }
+
+    #[test]
+    fn verify_u64_backend_nth_ends_gracefully_past_boundary() {
+        let mut fibonacci = Fibonacci::<u64>::new();
+        // `nth` sets `prev` from the lookahead value F(n+1), so unlike the walking `next` path
+        // (which only ever needs F(93) itself), the seek to index 93 also needs F(94), which
+        // overflows u64 -- so the highest index a u64-backed `nth` can reach is one less than
+        // what walking there with `next` can reach.
+        assert_eq!(fibonacci.nth(92), Some(()));
+        assert_eq!(fibonacci.current().unwrap(), 7540113804746346429);
+        assert_eq!(fibonacci.nth(93), None);
+        assert_eq!(fibonacci.current().unwrap(), 7540113804746346429);
+    }
 }
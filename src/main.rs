@@ -1,43 +1,251 @@
-use actix_session::CookieSession;
-use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use actix_session::{CookieSession, Session};
+use actix_web::{get, web, App, Error, HttpResponse, HttpServer, Responder};
+use futures::stream;
 use lazy_static::lazy_static;
+use num::BigInt;
+use serde::Deserialize;
+use std::fmt::Debug;
 use std::sync::Mutex;
 mod fibonacci;
-use fibonacci::Fibonacci;
+use fibonacci::{FibNum, Fibonacci};
 
 lazy_static! {
-    static ref FIB: Mutex<Fibonacci> = Mutex::new(Fibonacci::new());
+    // Only /nth/{n} (below) still reaches for a typed global directly; /next, /previous and
+    // /current are always served out of the caller's own session (see `handle_next` et al.).
+    static ref FIB_BIGINT: Mutex<Fibonacci<BigInt>> = Mutex::new(Fibonacci::new());
 }
 
-/// Get the next value for the shared sequence.
-#[get("/next")]
-async fn fib_next() -> impl Responder {
-    let mut fib = FIB.lock().unwrap();
-    let result = match fib.next() {
-        Some(value) => value.to_string(),
-        None => String::from("0")
+/// Name of the signed session cookie `actix-session` issues to track each caller's sequence.
+const SESSION_COOKIE_NAME: &str = "fib_chal";
+
+/// Key under which a session's Fibonacci call count is stored. Each backend gets its own
+/// segment of this key (see `session_key`) so a session can hold independent progress in the
+/// `u64`, `u128`, and `bigint` sequences at once.
+const SESSION_COUNT_KEY: &str = "count";
+
+/// The backend names accepted by the `type` query parameter, and what they default to when the
+/// parameter is omitted.
+#[derive(Deserialize)]
+struct BackendQuery {
+    #[serde(rename = "type")]
+    backend: Option<String>,
+}
+
+/// Resolves the requested backend name, defaulting to `bigint` to preserve the original
+/// unbounded behavior for callers who don't pass `?type=`.
+fn backend_name(query: &BackendQuery) -> String {
+    query.backend.clone().unwrap_or_else(|| String::from("bigint"))
+}
+
+/// Per-backend session key, so the `u64`, `u128`, and `bigint` sequences don't clobber each
+/// other's stored call count within the same session.
+fn session_key(backend: &str) -> String {
+    format!("{}_{}", SESSION_COUNT_KEY, backend)
+}
+
+/// Rebuilds the `Fibonacci` a session was left at. Positive counts use the fast-doubling `nth`
+/// seek to one index short of the target and then a single `next`, rather than persisting (or
+/// replaying) their whole history; seeking straight to the target would also need the value
+/// after it, which a bounded backend may not hold even where walking there with `next` worked.
+/// Counts at or below zero fall into the negafibonacci extension, which `nth` doesn't reach, so
+/// those are walked back via `previous` instead. Returns `None` if the stored count is no longer
+/// representable in a bounded backend (it shouldn't be reachable in practice, since the count
+/// itself is only ever advanced by a prior, already-bounds-checked step, but `nth`/`next`/
+/// `previous` are checked here too rather than assuming that).
+fn fibonacci_at<T: FibNum>(count: i64) -> Option<Fibonacci<T>> {
+    let mut fib = Fibonacci::new();
+    if count > 0 {
+        if count > 1 {
+            fib.nth((count - 2) as u64)?;
+        }
+        fib.next()?;
+    } else {
+        for _ in 0..(-count) {
+            fib.previous()?;
+        }
+    }
+    Some(fib)
+}
+
+/// Response body returned when a bounded backend (`u64`, `u128`) has overflowed or underflowed:
+/// the sequence has genuinely ended there, so rather than silently clamping we tell the caller
+/// what happened and how to keep going.
+fn overflow_error(backend: &str) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().body(format!(
+        "the {} sequence has run past what that type can hold; request a larger type (e.g. `type=bigint`)",
+        backend
+    ))
+}
+
+fn handle_next<T: FibNum + ToString>(session: &Session, backend: &str) -> HttpResponse {
+    let key = session_key(backend);
+    let stored = session.get::<i64>(&key).unwrap_or(None).unwrap_or(0);
+    let result = match fibonacci_at::<T>(stored) {
+        Some(mut fib) => {
+            let value = fib.next();
+            let _ = session.set(&key, fib.count());
+            value
+        }
+        None => None
     };
-    HttpResponse::Ok().body(result.to_string())
+    match result {
+        Some(value) => HttpResponse::Ok().body(value.to_string()),
+        None => overflow_error(backend)
+    }
 }
 
-/// Get the previous value for the shared sequence.
-#[get("/previous")]
-async fn fib_previous() -> impl Responder {
-    let mut fib = FIB.lock().unwrap();
-    let result = match fib.previous() {
-        Some(value) => value.to_string(),
-        None => String::from("0")
+fn handle_previous<T: FibNum + ToString>(session: &Session, backend: &str) -> HttpResponse {
+    let key = session_key(backend);
+    let stored = session.get::<i64>(&key).unwrap_or(None).unwrap_or(0);
+    let result = match fibonacci_at::<T>(stored) {
+        Some(mut fib) => {
+            let value = fib.previous();
+            let _ = session.set(&key, fib.count());
+            value
+        }
+        None => None
     };
-    HttpResponse::Ok().body(result.to_string())
+    match result {
+        Some(value) => HttpResponse::Ok().body(value.to_string()),
+        None => overflow_error(backend)
+    }
+}
+
+fn handle_current<T: FibNum + ToString + Debug>(session: &Session, backend: &str) -> HttpResponse {
+    let key = session_key(backend);
+    let stored = session.get::<i64>(&key).unwrap_or(None).unwrap_or(0);
+    let result = fibonacci_at::<T>(stored).and_then(|fib| fib.current());
+    match result {
+        Some(value) => HttpResponse::Ok().body(value.to_string()),
+        None => overflow_error(backend)
+    }
 }
 
-/// Get the current value of the shared sequence. It's not cleaar from the requirements what
+/// Get the next value, advancing the caller's own independent sequence -- established in their
+/// session on this very call if they don't have one yet. The `type` query parameter (`u64`,
+/// `u128`, or `bigint`, default `bigint`) selects which typed sequence is served.
+#[get("/next")]
+async fn fib_next(session: Session, query: web::Query<BackendQuery>) -> impl Responder {
+    match backend_name(&query).as_str() {
+        "u64" => handle_next::<u64>(&session, "u64"),
+        "u128" => handle_next::<u128>(&session, "u128"),
+        "bigint" => handle_next::<BigInt>(&session, "bigint"),
+        other => HttpResponse::BadRequest().body(format!("unknown type `{}`; expected one of u64, u128, bigint", other))
+    }
+}
+
+/// Get the previous value, stepping the caller's own independent sequence backward --
+/// established in their session on this very call if they don't have one yet. The `type` query
+/// parameter selects which typed sequence is served.
+#[get("/previous")]
+async fn fib_previous(session: Session, query: web::Query<BackendQuery>) -> impl Responder {
+    match backend_name(&query).as_str() {
+        "u64" => handle_previous::<u64>(&session, "u64"),
+        "u128" => handle_previous::<u128>(&session, "u128"),
+        "bigint" => handle_previous::<BigInt>(&session, "bigint"),
+        other => HttpResponse::BadRequest().body(format!("unknown type `{}`; expected one of u64, u128, bigint", other))
+    }
+}
+
+/// Get the current value of the caller's sequence. It's not cleaar from the requirements what
 /// happens in the event that the first call is to /current. Therefore, calling /current before
 /// calling /next will yield 0 even though the sequence has not been formall progressed in the 0
-/// position. This can be changed trivially if needed.
+/// position. This can be changed trivially if needed. The `type` query parameter selects which
+/// typed sequence is served.
 #[get("/current")]
-async fn fib_current() -> impl Responder {
-    let fib = FIB.lock().unwrap();
+async fn fib_current(session: Session, query: web::Query<BackendQuery>) -> impl Responder {
+    match backend_name(&query).as_str() {
+        "u64" => handle_current::<u64>(&session, "u64"),
+        "u128" => handle_current::<u128>(&session, "u128"),
+        "bigint" => handle_current::<BigInt>(&session, "bigint"),
+        other => HttpResponse::BadRequest().body(format!("unknown type `{}`; expected one of u64, u128, bigint", other))
+    }
+}
+
+/// The `start`/`count` query parameters accepted by `/range`, plus the same `type` backend
+/// selector as the single-value routes.
+#[derive(Deserialize)]
+struct RangeQuery {
+    start: u64,
+    count: u64,
+    #[serde(rename = "type")]
+    backend: Option<String>
+}
+
+/// Upper bound on how many values a single `/range` call will return, regardless of the
+/// requested `count`, so a client can't force the server to materialize an unbounded response.
+const MAX_RANGE_COUNT: u64 = 10_000;
+
+/// Lazily builds the JSON-array chunks for `/range`'s streamed response: an opening bracket,
+/// then one comma-prefixed quoted value pulled from `fib` at a time (its current value, then
+/// `count - 1` more via `Iterator::take`), then a closing bracket. Because this is all built out
+/// of lazy `std::iter` combinators rather than a collected `Vec`, nothing beyond `fib`'s initial
+/// `nth` seek is computed before the first chunk is polled off the stream, and each later value
+/// is only computed as the previous one is sent.
+fn range_chunks<T: FibNum + ToString + 'static>(
+    mut fib: Fibonacci<T>,
+    count: u64
+) -> impl Iterator<Item = Result<web::Bytes, Error>> {
+    let first = if count > 0 { fib.current() } else { None };
+    let values = first.into_iter().chain(fib.take(count.saturating_sub(1) as usize));
+    let mut is_first = true;
+    std::iter::once(Ok(web::Bytes::from_static(b"[")))
+        .chain(values.map(move |value| {
+            let prefix = if is_first { "" } else { "," };
+            is_first = false;
+            let value = value.to_string();
+            Ok(web::Bytes::from(format!("{}\"{}\"", prefix, value)))
+        }))
+        .chain(std::iter::once(Ok(web::Bytes::from_static(b"]"))))
+}
+
+/// Returns `count` consecutive Fibonacci values starting at index `start`, as a streamed JSON
+/// array of decimal strings, so arbitrarily large counts don't have to be fully materialized in
+/// memory before the first bytes go out -- each value is only computed as the stream is polled
+/// for it. `count` is capped at `MAX_RANGE_COUNT`. The `type` query parameter selects which typed
+/// backend computes the range; for a bounded backend the array ends early, without an error, at
+/// the last value that type can hold.
+#[get("/range")]
+async fn fib_range(query: web::Query<RangeQuery>) -> impl Responder {
+    let count = query.count.min(MAX_RANGE_COUNT);
+    let backend = backend_name(&BackendQuery { backend: query.backend.clone() });
+    match backend.as_str() {
+        "u64" => {
+            let mut fib = Fibonacci::<u64>::new();
+            if fib.nth(query.start).is_none() {
+                return overflow_error("u64");
+            }
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .streaming(stream::iter(range_chunks(fib, count)))
+        }
+        "u128" => {
+            let mut fib = Fibonacci::<u128>::new();
+            if fib.nth(query.start).is_none() {
+                return overflow_error("u128");
+            }
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .streaming(stream::iter(range_chunks(fib, count)))
+        }
+        "bigint" => {
+            let mut fib = Fibonacci::<BigInt>::new();
+            fib.nth(query.start);
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .streaming(stream::iter(range_chunks(fib, count)))
+        }
+        other => HttpResponse::BadRequest()
+            .body(format!("unknown type `{}`; expected one of u64, u128, bigint", other))
+    }
+}
+
+/// Jump the shared `BigInt` sequence straight to index `n` in O(log n) time via fast doubling
+/// and return the value found there.
+#[get("/nth/{n}")]
+async fn fib_nth(n: web::Path<u64>) -> impl Responder {
+    let mut fib = FIB_BIGINT.lock().unwrap();
+    fib.nth(n.into_inner());
     HttpResponse::Ok().body(fib.current().unwrap().to_string())
 }
 
@@ -46,19 +254,20 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(|| {
         App::new()
             .wrap(
-                // This session is not currently being used because the implementation and testing
-                // are outside the written scope of this project. However, the API could be
-                // extended to have session-based `current` numbers to serve as the input for
-                // pregression instead of the universal mechanism being used now.
+                // Every client gets its own independent sequence: /next, /previous and /current
+                // read and write the call count stashed in this signed cookie, establishing it
+                // on whichever call happens to be the caller's first.
                 CookieSession::signed(&[0; 32])
                     .domain("www.somefibonaccisite.pro")
-                    .name("fib_chal")
+                    .name(SESSION_COOKIE_NAME)
                     .path("/")
                     .secure(true)
             )
             .service(fib_next)
             .service(fib_previous)
             .service(fib_current)
+            .service(fib_nth)
+            .service(fib_range)
     })
     .bind("0.0.0.0:8080")?
     .run()